@@ -45,6 +45,12 @@ const PIX_WIDTH: f32 = 450.0;
 /// Maximum pixel height of DMS images
 const PIX_HEIGHT: f32 = 100.0;
 
+/// Maximum number of pages (including off pages) to render
+const MAX_PAGES: usize = 64;
+
+/// Maximum delay of a .gif frame (deciseconds)
+const MAX_DELAY_DS: u16 = u16::MAX / 10;
+
 /// Unknown resource error
 #[derive(Debug)]
 pub struct UnknownResourceError(String);
@@ -67,6 +73,28 @@ impl UnknownResourceError {
     }
 }
 
+/// Too many pages error
+#[derive(Debug)]
+pub struct TooManyPagesError(usize);
+
+impl fmt::Display for TooManyPagesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Too many pages: limit {}", self.0)
+    }
+}
+
+impl std::error::Error for TooManyPagesError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl TooManyPagesError {
+    fn new(limit: usize) -> Box<Self> {
+        Box::new(TooManyPagesError(limit))
+    }
+}
+
 /// Sign configuration
 #[derive(Deserialize, Serialize)]
 struct SignConfig {
@@ -147,6 +175,13 @@ fn rgb_from_i32(rgb: i32) -> (u8, u8, u8) {
     (r, g, b)
 }
 
+/// Check if two rasters have identical pixels
+fn is_same_raster(r0: &Raster<SRgb8>, r1: &Raster<SRgb8>) -> bool {
+    r0.width() == r1.width()
+        && r0.height() == r1.height()
+        && r0.as_u8_slice() == r1.as_u8_slice()
+}
+
 impl SignConfig {
     /// Load sign configurations from a JSON file
     fn load(dir: &Path) -> Result<HashMap<String, SignConfig>> {
@@ -336,10 +371,9 @@ impl SignConfig {
         palette.set_threshold_fn(palette_threshold_rgb8_256);
         palette.set_entry(SRgb8::default());
         let mut steps = Vec::new();
-        let pages = self.pages(msg_data, multi)?;
+        let pages = self.render_pages(msg_data, multi)?;
         let (w, h) = self.calculate_size()?;
-        for page in pages {
-            let (raster, delay_ds) = page?;
+        for (raster, delay_ds) in pages {
             let delay = delay_ds * 10;
             let step = self.make_face_step(raster, &mut palette, w, h, delay);
             steps.push(step);
@@ -356,6 +390,35 @@ impl SignConfig {
         Ok(())
     }
 
+    /// Render all pages of a sign message.
+    ///
+    /// Consecutive pages with identical pixels are merged by extending the
+    /// delay of the first one, up to [MAX_DELAY_DS].  Rendering stops with an
+    /// error once [MAX_PAGES] is exceeded.
+    fn render_pages(
+        &self,
+        msg_data: &MsgData,
+        multi: &str,
+    ) -> Result<Vec<(Raster<SRgb8>, u16)>> {
+        let mut pages: Vec<(Raster<SRgb8>, u16)> = Vec::new();
+        for (i, page) in self.pages(msg_data, multi)?.enumerate() {
+            if i >= MAX_PAGES {
+                return Err(TooManyPagesError::new(MAX_PAGES));
+            }
+            let (raster, delay_ds) = page?;
+            if let Some((prev, prev_ds)) = pages.last_mut() {
+                if delay_ds <= MAX_DELAY_DS.saturating_sub(*prev_ds)
+                    && is_same_raster(prev, &raster)
+                {
+                    *prev_ds += delay_ds;
+                    continue;
+                }
+            }
+            pages.push((raster, delay_ds));
+        }
+        Ok(pages)
+    }
+
     /// Create pages for a sign config.
     fn pages<'a>(
         &self,
@@ -827,3 +890,215 @@ pub fn render_all(dir: &Path) -> Result<()> {
     images.remove_expired()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use gift::{block::Block, Decoder};
+
+    fn sign_config(char_width: i32, char_height: i32) -> SignConfig {
+        SignConfig {
+            name: "test".to_string(),
+            face_width: 3000,
+            face_height: 1000,
+            border_horiz: 100,
+            border_vert: 100,
+            pitch_horiz: 50,
+            pitch_vert: 50,
+            pixel_width: 50,
+            pixel_height: 14,
+            char_width,
+            char_height,
+            monochrome_foreground: 0xFFD000,
+            monochrome_background: 0,
+            color_scheme: "monochrome1Bit".to_string(),
+            default_font: None,
+        }
+    }
+
+    /// Font with a solid 5x7 glyph for "A"
+    const FONT: &str = r#"[{
+        "number": 1,
+        "name": "test",
+        "height": 7,
+        "char_spacing": 1,
+        "line_spacing": 0,
+        "characters": [{ "number": 65, "width": 5, "bitmap": "/////+A=" }],
+        "version_id": 0
+    }]"#;
+
+    fn msg_data(attrs: HashMap<String, String>) -> MsgData {
+        let mut fonts = FontCache::default();
+        let fts: Vec<Font> = serde_json::from_str(FONT).unwrap();
+        for f in fts {
+            fonts.insert(f);
+        }
+        MsgData {
+            attrs,
+            configs: HashMap::new(),
+            fonts,
+            graphics: GraphicCache::default(),
+        }
+    }
+
+    fn render(
+        cfg: &SignConfig,
+        msg_data: &MsgData,
+        multi: &str,
+    ) -> Vec<(Raster<SRgb8>, u16)> {
+        let pages = cfg.pages(msg_data, multi).unwrap();
+        pages.collect::<std::result::Result<_, _>>().unwrap()
+    }
+
+    /// Get the bounds (x0, y0, x1, y1) of lit pixels on a page
+    fn lit_bounds(page: &Raster<SRgb8>) -> Option<(u32, u32, u32, u32)> {
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for y in 0..page.height() {
+            for x in 0..page.width() {
+                if page.pixel(x as i32, y as i32) != SRgb8::default() {
+                    bounds = Some(match bounds {
+                        Some((x0, y0, x1, y1)) => {
+                            (x0.min(x), y0.min(y), x1.max(x), y1.max(y))
+                        }
+                        None => (x, y, x, y),
+                    });
+                }
+            }
+        }
+        bounds
+    }
+
+    #[test]
+    fn justification_defaults() {
+        let msg_data = msg_data(HashMap::new());
+        assert_eq!(
+            msg_data.page_justification_default(),
+            JustificationPage::Top
+        );
+        assert_eq!(
+            msg_data.line_justification_default(),
+            JustificationLine::Center
+        );
+        // Character-matrix: centered within 10 cells of 5 pixels
+        let pages = render(&sign_config(5, 7), &msg_data, "A");
+        assert_eq!(pages.len(), 1);
+        assert_eq!(lit_bounds(&pages[0].0), Some((20, 0, 24, 6)));
+        // Full-matrix: centered within 50 pixels
+        let pages = render(&sign_config(0, 0), &msg_data, "A");
+        assert_eq!(pages.len(), 1);
+        assert_eq!(lit_bounds(&pages[0].0), Some((22, 0, 26, 6)));
+    }
+
+    #[test]
+    fn page_limit() {
+        let msg_data = msg_data(HashMap::new());
+        let cfg = sign_config(5, 7);
+        let multi = "[np]".repeat(MAX_PAGES - 1);
+        let pages = cfg.render_pages(&msg_data, &multi).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].1, 20 * MAX_PAGES as u16);
+        let multi = "[np]".repeat(MAX_PAGES);
+        match cfg.render_pages(&msg_data, &multi) {
+            Err(e) => assert!(e.is::<TooManyPagesError>()),
+            Ok(_) => panic!("page limit not enforced"),
+        }
+    }
+
+    #[test]
+    fn merge_pages() {
+        let msg_data = msg_data(HashMap::new());
+        let cfg = sign_config(5, 7);
+        let pages = cfg.render_pages(&msg_data, "[np]").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].1, 40);
+        let multi = "[pb1][np][pb1][np][pb0]";
+        let pages = cfg.render_pages(&msg_data, multi).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].1, 40);
+        assert_eq!(pages[1].1, 20);
+        assert!(!is_same_raster(&pages[0].0, &pages[1].0));
+        let multi = "[pt20o5][cr1,1,5,7,1]";
+        let pages = cfg.render_pages(&msg_data, multi).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].1, 20);
+        assert_eq!(pages[1].1, 5);
+    }
+
+    #[test]
+    fn merge_delay_limit() {
+        let msg_data = msg_data(HashMap::new());
+        let cfg = sign_config(5, 7);
+        let multi = format!("[pt255]{}", "[np]".repeat(25));
+        let pages = cfg.render_pages(&msg_data, &multi).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].1, 25 * 255);
+        assert_eq!(pages[1].1, 255);
+        assert!(pages[0].1 + pages[1].1 > MAX_DELAY_DS);
+    }
+
+    /// Get the frame delays (centiseconds) and loop count of a .gif
+    fn gif_frames(gif: &[u8]) -> (Vec<u16>, Option<u16>) {
+        let mut delays = Vec::new();
+        let mut loop_count = None;
+        for block in Decoder::new(gif).into_blocks() {
+            match block.unwrap() {
+                Block::GraphicControl(c) => delays.push(c.delay_time_cs()),
+                Block::Application(a) => loop_count = a.loop_count(),
+                _ => (),
+            }
+        }
+        (delays, loop_count)
+    }
+
+    fn render_gif(
+        cfg: &SignConfig,
+        msg_data: &MsgData,
+        multi: &str,
+    ) -> Vec<u8> {
+        let mut gif = Vec::new();
+        cfg.render_sign_config(&mut gif, multi, msg_data).unwrap();
+        gif
+    }
+
+    #[test]
+    fn merge_gif_frames() {
+        let msg_data = msg_data(HashMap::new());
+        let cfg = sign_config(5, 7);
+        let gif = render_gif(&cfg, &msg_data, "[np]");
+        assert_eq!(gif_frames(&gif), (vec![400], None));
+        let gif = render_gif(&cfg, &msg_data, "[pb1][np][pb1][np][pb0]");
+        assert_eq!(gif_frames(&gif), (vec![400, 200], Some(0)));
+    }
+
+    #[test]
+    fn blinking_page() {
+        let msg_data = msg_data(HashMap::new());
+        let cfg = sign_config(5, 7);
+        let gif = render_gif(&cfg, &msg_data, "[pt20o5]A");
+        assert_eq!(gif_frames(&gif), (vec![200, 50], Some(0)));
+    }
+
+    #[test]
+    fn justification_attributes() {
+        let mut attrs = HashMap::new();
+        attrs.insert(
+            "dms_default_justification_page".to_string(),
+            "4".to_string(),
+        );
+        attrs.insert(
+            "dms_default_justification_line".to_string(),
+            "4".to_string(),
+        );
+        let msg_data = msg_data(attrs);
+        assert_eq!(
+            msg_data.page_justification_default(),
+            JustificationPage::Bottom
+        );
+        assert_eq!(
+            msg_data.line_justification_default(),
+            JustificationLine::Right
+        );
+        let pages = render(&sign_config(0, 0), &msg_data, "A");
+        assert_eq!(lit_bounds(&pages[0].0), Some((45, 7, 49, 13)));
+    }
+}